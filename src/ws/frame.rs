@@ -3,6 +3,7 @@ use std::iter::FromIterator;
 use bytes::{Bytes, BytesMut, BufMut};
 use byteorder::{ByteOrder, BigEndian, NetworkEndian};
 use futures::{Async, Poll, Stream};
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
 use rand;
 
 use body::Binary;
@@ -13,12 +14,48 @@ use ws::ProtocolError;
 use ws::proto::{OpCode, CloseCode};
 use ws::mask::apply_mask;
 
+/// Bytes a permessage-deflate sender strips off the end of a deflated
+/// message (RFC 7692 section 7.2.1) and that a receiver must append before
+/// inflating it back (RFC 7692 section 7.2.2).
+const DEFLATE_TRAILER: &[u8] = &[0x00, 0x00, 0xff, 0xff];
+
+/// The RSV1 bit (RFC 7692 permessage-deflate).
+const RSV1: u8 = 0x40;
+
+/// Mask covering all three reserved bits (RSV1-3) of the first frame byte.
+const RSV_MASK: u8 = 0x70;
+
 /// A struct representing a `WebSocket` frame.
 #[derive(Debug)]
 pub struct Frame {
     finished: bool,
     opcode: OpCode,
     payload: Binary,
+    close_reason: Option<CloseReason>,
+    /// RSV1 was set on this physical frame, i.e. per RFC 7692 it either is
+    /// or opens a permessage-deflate compressed message. Taken from each
+    /// frame's own RSV1 bit, which a conforming peer sets only on the first
+    /// frame of a message; `Frame::parse` does not reject RSV1 on a
+    /// `Continuation` frame. `FrameReassembler` uses the flag of the opening
+    /// frame for the whole message and inflates once it has been reassembled.
+    compressed: bool,
+}
+
+/// The close code and, if present, UTF-8 reason parsed out of a Close
+/// frame's payload (RFC 6455 section 5.5.1).
+#[derive(Debug, Clone, PartialEq)]
+pub struct CloseReason {
+    pub code: CloseCode,
+    pub description: Option<String>,
+}
+
+/// A frame header and its (already masked, if requested) payload, kept as
+/// separate buffers so a transport can `write_vectored`/`writev` them
+/// without the concatenating copy `Frame::message` pays for.
+#[derive(Debug)]
+pub struct EncodedFrame {
+    pub head: Bytes,
+    pub payload: Bytes,
 }
 
 impl Frame {
@@ -28,6 +65,11 @@ impl Frame {
         (self.finished, self.opcode, self.payload)
     }
 
+    /// The code and reason parsed from a Close frame's payload, if any.
+    pub fn close_reason(&self) -> Option<&CloseReason> {
+        self.close_reason.as_ref()
+    }
+
     /// Create a new Close control frame.
     #[inline]
     pub fn close(code: CloseCode, reason: &str, genmask: bool) -> Binary {
@@ -48,11 +90,83 @@ impl Frame {
         Frame::message(payload, OpCode::Close, true, genmask)
     }
 
+    /// Reject a non-zero RSV1-3 bit unless its extension has been
+    /// negotiated, per RFC 6455 section 5.2 ("MUST be 0 unless an extension
+    /// is negotiated that defines meanings for non-zero values").
+    /// `allowed_rsv` is the subset of `RSV_MASK` permitted, e.g. `RSV1` once
+    /// permessage-deflate has been negotiated.
+    fn validate_rsv(first: u8, allowed_rsv: u8) -> Result<(), ProtocolError> {
+        let rsv = first & RSV_MASK;
+        if rsv & !allowed_rsv != 0 {
+            return Err(ProtocolError::InvalidRsvBits(rsv))
+        }
+        Ok(())
+    }
+
+    /// Resolve an opcode nibble, explicitly rejecting the reserved data
+    /// (0x3-0x7) and control (0xB-0xF) ranges instead of folding them into
+    /// `OpCode::Bad`.
+    fn validate_opcode(nibble: u8) -> Result<OpCode, ProtocolError> {
+        match nibble {
+            0x3..=0x7 | 0xB..=0xF => Err(ProtocolError::InvalidOpcode(nibble)),
+            _ => match OpCode::from(nibble) {
+                OpCode::Bad => Err(ProtocolError::InvalidOpcode(nibble)),
+                opcode => Ok(opcode),
+            }
+        }
+    }
+
+    /// Control frames must not be fragmented (RFC 6455 section 5.4).
+    fn validate_not_fragmented_control(opcode: OpCode, finished: bool) -> Result<(), ProtocolError> {
+        match opcode {
+            OpCode::Ping | OpCode::Pong | OpCode::Close if !finished =>
+                Err(ProtocolError::FragmentedControl),
+            _ => Ok(()),
+        }
+    }
+
+    /// Codes the spec reserves and forbids from appearing on the wire
+    /// (RFC 6455 section 7.4.1/7.4.2).
+    fn is_valid_close_code(code: u16) -> bool {
+        match code {
+            0..=999 => false,
+            1004 | 1005 | 1006 | 1015 => false,
+            1016..=2999 => false,
+            _ => true,
+        }
+    }
+
+    /// Decode a Close frame's payload into its status code and UTF-8 reason,
+    /// per RFC 6455 section 5.5.1. An empty payload carries neither.
+    fn parse_close_payload(data: &[u8]) -> Result<Option<CloseReason>, ProtocolError> {
+        if data.is_empty() {
+            return Ok(None)
+        }
+        if data.len() == 1 {
+            return Err(ProtocolError::InvalidLength(data.len()))
+        }
+
+        let code_raw = NetworkEndian::read_u16(&data[..2]);
+        if !Frame::is_valid_close_code(code_raw) {
+            return Err(ProtocolError::InvalidCloseCode(code_raw))
+        }
+
+        let description = if data.len() > 2 {
+            Some(String::from_utf8(data[2..].to_vec())
+                 .map_err(|_| ProtocolError::InvalidUtf8)?)
+        } else {
+            None
+        };
+
+        Ok(Some(CloseReason { code: code_raw.into(), description }))
+    }
+
     #[cfg_attr(feature="cargo-clippy", allow(type_complexity))]
     fn read_copy_md<S>(pl: &mut PayloadHelper<S>,
                        server: bool,
-                       max_size: usize
-    ) -> Poll<Option<(usize, bool, OpCode, usize, Option<u32>)>, ProtocolError>
+                       max_size: usize,
+                       allowed_rsv: u8,
+    ) -> Poll<Option<(usize, bool, OpCode, usize, Option<u32>, bool)>, ProtocolError>
         where S: Stream<Item=Bytes, Error=PayloadError>
     {
         let mut idx = 2;
@@ -65,6 +179,11 @@ impl Frame {
         let second = buf[1];
         let finished = first & 0x80 != 0;
 
+        Frame::validate_rsv(first, allowed_rsv)?;
+
+        // permessage-deflate (RFC 7692): RSV1 marks a compressed message
+        let compressed = first & RSV1 != 0;
+
         // check masking
         let masked = second & 0x80 != 0;
         if !masked && server {
@@ -74,11 +193,8 @@ impl Frame {
         }
 
         // Op code
-        let opcode = OpCode::from(first & 0x0F);
-
-        if let OpCode::Bad = opcode {
-            return Err(ProtocolError::InvalidOpcode(first & 0x0F))
-        }
+        let opcode = Frame::validate_opcode(first & 0x0F)?;
+        Frame::validate_not_fragmented_control(opcode, finished)?;
 
         let len = second & 0x7F;
         let length = if len == 126 {
@@ -123,11 +239,11 @@ impl Frame {
             None
         };
 
-        Ok(Async::Ready(Some((idx, finished, opcode, length, mask))))
+        Ok(Async::Ready(Some((idx, finished, opcode, length, mask, compressed))))
     }
 
-    fn read_chunk_md(chunk: &[u8], server: bool, max_size: usize)
-                     -> Poll<(usize, bool, OpCode, usize, Option<u32>), ProtocolError>
+    fn read_chunk_md(chunk: &[u8], server: bool, max_size: usize, allowed_rsv: u8)
+                     -> Poll<(usize, bool, OpCode, usize, Option<u32>, bool), ProtocolError>
     {
         let chunk_len = chunk.len();
 
@@ -140,6 +256,11 @@ impl Frame {
         let second = chunk[1];
         let finished = first & 0x80 != 0;
 
+        Frame::validate_rsv(first, allowed_rsv)?;
+
+        // permessage-deflate (RFC 7692): RSV1 marks a compressed message
+        let compressed = first & RSV1 != 0;
+
         // check masking
         let masked = second & 0x80 != 0;
         if !masked && server {
@@ -149,11 +270,8 @@ impl Frame {
         }
 
         // Op code
-        let opcode = OpCode::from(first & 0x0F);
-
-        if let OpCode::Bad = opcode {
-            return Err(ProtocolError::InvalidOpcode(first & 0x0F))
-        }
+        let opcode = Frame::validate_opcode(first & 0x0F)?;
+        Frame::validate_not_fragmented_control(opcode, finished)?;
 
         let len = second & 0x7F;
         let length = if len == 126 {
@@ -192,11 +310,23 @@ impl Frame {
             None
         };
 
-        Ok(Async::Ready((idx, finished, opcode, length, mask)))
+        Ok(Async::Ready((idx, finished, opcode, length, mask, compressed)))
     }
 
-    /// Parse the input stream into a frame.
-    pub fn parse<S>(pl: &mut PayloadHelper<S>, server: bool, max_size: usize)
+    /// Parse the input stream into a single physical frame.
+    ///
+    /// A frame's `compressed` flag is taken straight off its own RSV1 bit.
+    /// Per RFC 7692, that bit is only meaningful on the first frame of a
+    /// message, so for a fragmented compressed message every `Continuation`
+    /// frame comes back with `compressed == false` here even though it is
+    /// still part of a compressed message. This function never inflates —
+    /// `FrameReassembler` carries the flag from the opening frame across
+    /// fragments and inflates once the whole message has been reassembled.
+    ///
+    /// `allowed_rsv` is the subset of the RSV1-3 bits the caller has
+    /// negotiated an extension for; any other reserved bit fails the frame.
+    pub fn parse<S>(pl: &mut PayloadHelper<S>, server: bool, max_size: usize,
+                    allowed_rsv: u8)
                     -> Poll<Option<Frame>, ProtocolError>
         where S: Stream<Item=Bytes, Error=PayloadError>
     {
@@ -204,12 +334,12 @@ impl Frame {
         let result = match pl.get_chunk()? {
             Async::NotReady => return Ok(Async::NotReady),
             Async::Ready(None) => return Ok(Async::Ready(None)),
-            Async::Ready(Some(chunk)) => Frame::read_chunk_md(chunk, server, max_size)?,
+            Async::Ready(Some(chunk)) => Frame::read_chunk_md(chunk, server, max_size, allowed_rsv)?,
         };
 
-        let (idx, finished, opcode, length, mask) = match result {
+        let (idx, finished, opcode, length, mask, compressed) = match result {
             // we may need to join several chunks
-            Async::NotReady => match Frame::read_copy_md(pl, server, max_size)? {
+            Async::NotReady => match Frame::read_copy_md(pl, server, max_size, allowed_rsv)? {
                 Async::Ready(Some(item)) => item,
                 Async::NotReady => return Ok(Async::NotReady),
                 Async::Ready(None) => return Ok(Async::Ready(None)),
@@ -229,7 +359,7 @@ impl Frame {
         // no need for body
         if length == 0 {
             return Ok(Async::Ready(Some(Frame {
-                finished, opcode, payload: Binary::from("") })));
+                finished, opcode, payload: Binary::from(""), close_reason: None, compressed })));
         }
 
         let data = match pl.read_exact(length)? {
@@ -257,20 +387,143 @@ impl Frame {
             apply_mask(p, mask);
         }
 
-        Ok(Async::Ready(Some(Frame {
-            finished, opcode, payload: data.into() })))
+        let close_reason = if let OpCode::Close = opcode {
+            Frame::parse_close_payload(&data)?
+        } else {
+            None
+        };
+
+        Ok(Async::Ready(Some(Frame { finished, opcode, payload: data.into(), close_reason, compressed })))
+    }
+
+    /// Inflate a permessage-deflate compressed payload (RFC 7692 section
+    /// 7.2.2). `max_size` bounds the inflated output independently of the
+    /// compressed input length, so a small compressed message can't be used
+    /// to force an arbitrarily large allocation (a decompression bomb).
+    fn inflate(decompress: &mut Decompress, data: &[u8], max_size: usize) -> Result<Binary, ProtocolError> {
+        let mut input = BytesMut::with_capacity(data.len() + DEFLATE_TRAILER.len());
+        input.extend_from_slice(data);
+        input.extend_from_slice(DEFLATE_TRAILER);
+
+        let mut out = Vec::with_capacity(data.len() * 2);
+        let mut consumed = 0;
+        loop {
+            let in_before = decompress.total_in();
+            let out_before = decompress.total_out();
+            let start = out.len();
+            out.resize(start + 4096, 0);
+
+            let status = decompress
+                .decompress(&input[consumed..], &mut out[start..], FlushDecompress::Sync)
+                .map_err(|_| ProtocolError::InvalidCompression)?;
+
+            consumed += (decompress.total_in() - in_before) as usize;
+
+            let produced = (decompress.total_out() - out_before) as usize;
+            out.truncate(start + produced);
+
+            if out.len() > max_size {
+                return Err(ProtocolError::Overflow)
+            }
+
+            match status {
+                Status::StreamEnd => break,
+                Status::Ok | Status::BufError => {
+                    if consumed >= input.len() && produced < 4096 {
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(Binary::from(out))
+    }
+
+    /// Resolve a reassembled message's final payload: inflate it if the
+    /// opening frame had `compressed` set, otherwise pass it through as-is.
+    /// Used by `FrameReassembler::process` once a message's last fragment
+    /// (or its only frame) has arrived.
+    fn finish_payload(buf: BytesMut, compressed: bool, decompress: Option<&mut Decompress>,
+                      max_size: usize) -> Result<Binary, ProtocolError>
+    {
+        if compressed {
+            let decompress = decompress.ok_or(ProtocolError::InvalidCompression)?;
+            Frame::inflate(decompress, &buf, max_size)
+        } else {
+            Ok(buf.into())
+        }
     }
 
     /// Generate binary representation
     pub fn message<B: Into<Binary>>(data: B, code: OpCode,
                                     finished: bool, genmask: bool) -> Binary
     {
-        let payload = data.into();
         let one: u8 = if finished {
             0x80 | Into::<u8>::into(code)
         } else {
             code.into()
         };
+        Frame::build_message(data.into(), one, genmask)
+    }
+
+    /// Generate binary representation of a permessage-deflate compressed
+    /// message, setting RSV1 on the first frame as RFC 7692 requires.
+    ///
+    /// `compress` must be reused across calls unless the extension was
+    /// negotiated with context takeover disabled.
+    pub fn compressed_message<B: Into<Binary>>(data: B, code: OpCode, finished: bool,
+                                               genmask: bool, compress: &mut Compress)
+                                               -> Result<Binary, ProtocolError>
+    {
+        let one: u8 = if finished {
+            0x80 | 0x40 | Into::<u8>::into(code)
+        } else {
+            0x40 | Into::<u8>::into(code)
+        };
+        let payload = Frame::deflate(compress, data.into().as_ref())?;
+        Ok(Frame::build_message(payload, one, genmask))
+    }
+
+    /// Deflate a payload for permessage-deflate, stripping the trailing
+    /// 0x00 0x00 0xff 0xff that a Sync-flushed DEFLATE stream always ends
+    /// with (RFC 7692 section 7.2.1).
+    fn deflate(compress: &mut Compress, data: &[u8]) -> Result<Binary, ProtocolError> {
+        let mut out = Vec::with_capacity(data.len());
+        let mut consumed = 0;
+        loop {
+            let in_before = compress.total_in();
+            let out_before = compress.total_out();
+            let start = out.len();
+            out.resize(start + 4096, 0);
+
+            let status = compress
+                .compress(&data[consumed..], &mut out[start..], FlushCompress::Sync)
+                .map_err(|_| ProtocolError::InvalidCompression)?;
+
+            consumed += (compress.total_in() - in_before) as usize;
+
+            let produced = (compress.total_out() - out_before) as usize;
+            out.truncate(start + produced);
+
+            match status {
+                Status::StreamEnd => break,
+                Status::Ok | Status::BufError => {
+                    if consumed >= data.len() && produced < 4096 {
+                        break;
+                    }
+                }
+            }
+        }
+
+        if out.ends_with(DEFLATE_TRAILER) {
+            let new_len = out.len() - DEFLATE_TRAILER.len();
+            out.truncate(new_len);
+        }
+
+        Ok(Binary::from(out))
+    }
+
+    fn build_message(payload: Binary, one: u8, genmask: bool) -> Binary {
         let payload_len = payload.len();
         let (two, p_len) = if genmask {
             (0x80, payload_len + 4)
@@ -319,6 +572,91 @@ impl Frame {
             buf.into()
         }
     }
+
+    /// Like `message`, but returns the header and the payload as separate
+    /// buffers for vectored I/O instead of one allocation sized for both.
+    ///
+    /// Masking, when `genmask` is set, is applied in place on `payload`
+    /// rather than into a freshly allocated buffer.
+    pub fn encode(mut payload: BytesMut, code: OpCode, finished: bool, genmask: bool) -> EncodedFrame {
+        let one: u8 = if finished {
+            0x80 | Into::<u8>::into(code)
+        } else {
+            code.into()
+        };
+
+        let mask = if genmask { Some(rand::random::<u32>()) } else { None };
+        if let Some(mask) = mask {
+            apply_mask(&mut payload, mask);
+        }
+
+        let head = Frame::encode_head(one, payload.len(), mask);
+        EncodedFrame { head, payload: payload.freeze() }
+    }
+
+    /// Split `data` into frames of at most `chunk_size` bytes, emitted as a
+    /// leading data frame of opcode `code` followed by `Continuation`
+    /// frames, the last one carrying the FIN bit. Lets a large payload be
+    /// streamed out frame by frame instead of buffered into one.
+    ///
+    /// Fails with `ProtocolError::InvalidChunkSize` if `chunk_size` is 0,
+    /// since `slice::chunks` cannot be called with a zero size.
+    pub fn encode_fragmented<B: Into<Binary>>(data: B, code: OpCode, genmask: bool,
+                                              chunk_size: usize) -> Result<Vec<EncodedFrame>, ProtocolError>
+    {
+        if chunk_size == 0 {
+            return Err(ProtocolError::InvalidChunkSize(chunk_size))
+        }
+
+        let payload = data.into();
+        let chunks: Vec<&[u8]> = if payload.is_empty() {
+            vec![&[][..]]
+        } else {
+            payload.as_ref().chunks(chunk_size).collect()
+        };
+
+        let last = chunks.len() - 1;
+        Ok(chunks.into_iter().enumerate().map(|(i, chunk)| {
+            let code = if i == 0 { code } else { OpCode::Continuation };
+            Frame::encode(BytesMut::from(chunk), code, i == last, genmask)
+        }).collect())
+    }
+
+    /// Build the FIN/opcode/length/mask-key bytes of a frame header. The
+    /// masking key, if any, is written in the same native byte order
+    /// `read_copy_md`/`read_chunk_md` read it back in.
+    fn encode_head(one: u8, payload_len: usize, mask: Option<u32>) -> Bytes {
+        let two = if mask.is_some() { 0x80 } else { 0 };
+        let mask_len = if mask.is_some() { 4 } else { 0 };
+
+        let mut head = if payload_len < 126 {
+            let mut head = BytesMut::with_capacity(2 + mask_len);
+            head.put_slice(&[one, two | payload_len as u8]);
+            head
+        } else if payload_len <= 65_535 {
+            let mut head = BytesMut::with_capacity(4 + mask_len);
+            head.put_slice(&[one, two | 126]);
+            let mut len_buf = [0u8; 2];
+            BigEndian::write_u16(&mut len_buf, payload_len as u16);
+            head.put_slice(&len_buf);
+            head
+        } else {
+            let mut head = BytesMut::with_capacity(10 + mask_len);
+            head.put_slice(&[one, two | 127]);
+            let mut len_buf = [0u8; 8];
+            BigEndian::write_u64(&mut len_buf, payload_len as u64);
+            head.put_slice(&len_buf);
+            head
+        };
+
+        if let Some(mask) = mask {
+            let mut mask_buf = [0u8; 4];
+            unsafe { *(mask_buf.as_mut_ptr() as *mut u32) = mask; }
+            head.put_slice(&mask_buf);
+        }
+
+        head.freeze()
+    }
 }
 
 impl Default for Frame {
@@ -327,6 +665,96 @@ impl Default for Frame {
             finished: true,
             opcode: OpCode::Close,
             payload: Binary::from(&b""[..]),
+            close_reason: None,
+            compressed: false,
+        }
+    }
+}
+
+/// Reassembles a logical WebSocket message (RFC 6455 section 5.4) out of
+/// the physical frames returned one at a time by `Frame::parse`, and is
+/// also where a permessage-deflate compressed message gets inflated
+/// (RFC 7692 section 7.2.2) — exactly once, against the concatenated
+/// fragments, rather than per physical frame.
+///
+/// A data frame (`Text`/`Binary`) with `finished == false` opens a message
+/// that subsequent `Continuation` frames extend until one arrives with the
+/// FIN bit set. Control frames (`Ping`/`Pong`/`Close`) may legally appear
+/// between fragments and are passed through unchanged. Unlike the
+/// per-frame `max_size` already enforced in `Frame::parse`, `max_message_size`
+/// bounds both the sum of all fragment lengths and the inflated size of a
+/// compressed message, so a small compressed message can't expand into an
+/// unbounded allocation.
+pub struct FrameReassembler {
+    max_message_size: usize,
+    current: Option<(OpCode, bool, BytesMut)>,
+}
+
+impl FrameReassembler {
+
+    /// Create a reassembler that fails a message once its fragments'
+    /// combined (or, if compressed, inflated) length exceeds
+    /// `max_message_size`.
+    pub fn new(max_message_size: usize) -> FrameReassembler {
+        FrameReassembler { max_message_size, current: None }
+    }
+
+    /// Feed one physical frame. Returns `Some(Frame)` once a complete
+    /// logical message has been reassembled (or a control frame has been
+    /// passed through), `None` while a fragmented message is still open.
+    ///
+    /// `decompress` is the permessage-deflate inflater negotiated at
+    /// handshake time, if any. It must be reused across calls unless the
+    /// extension was negotiated with context takeover disabled, since a
+    /// compressed message's DEFLATE stream carries state from prior messages.
+    pub fn process(&mut self, frame: Frame, decompress: Option<&mut Decompress>)
+                   -> Result<Option<Frame>, ProtocolError>
+    {
+        let Frame { finished, opcode, payload, close_reason, compressed } = frame;
+
+        match opcode {
+            OpCode::Continuation => {
+                let (open_code, open_compressed, mut buf) = match self.current.take() {
+                    Some(item) => item,
+                    None => return Err(ProtocolError::UnexpectedContinuation),
+                };
+
+                let total = buf.len() + payload.len();
+                if total > self.max_message_size {
+                    return Err(ProtocolError::Overflow)
+                }
+                buf.extend_from_slice(payload.as_ref());
+
+                if finished {
+                    let payload = Frame::finish_payload(
+                        buf, open_compressed, decompress, self.max_message_size)?;
+                    Ok(Some(Frame {
+                        finished: true, opcode: open_code, payload, close_reason: None, compressed: false }))
+                } else {
+                    self.current = Some((open_code, open_compressed, buf));
+                    Ok(None)
+                }
+            }
+            OpCode::Text | OpCode::Binary => {
+                if self.current.is_some() {
+                    return Err(ProtocolError::ContinuationExpected)
+                }
+                if payload.len() > self.max_message_size {
+                    return Err(ProtocolError::Overflow)
+                }
+
+                if finished {
+                    let buf = BytesMut::from(payload.as_ref());
+                    let payload = Frame::finish_payload(
+                        buf, compressed, decompress, self.max_message_size)?;
+                    Ok(Some(Frame { finished: true, opcode, payload, close_reason: None, compressed: false }))
+                } else {
+                    self.current = Some((opcode, compressed, BytesMut::from(payload.as_ref())));
+                    Ok(None)
+                }
+            }
+            // control frames may interleave with fragments and need no reassembly
+            _ => Ok(Some(Frame { finished, opcode, payload, close_reason, compressed })),
         }
     }
 }
@@ -372,13 +800,13 @@ mod tests {
     fn test_parse() {
         let mut buf = PayloadHelper::new(
             once(Ok(BytesMut::from(&[0b00000001u8, 0b00000001u8][..]).freeze())));
-        assert!(is_none(Frame::parse(&mut buf, false, 1024)));
+        assert!(is_none(Frame::parse(&mut buf, false, 1024, 0)));
 
         let mut buf = BytesMut::from(&[0b00000001u8, 0b00000001u8][..]);
         buf.extend(b"1");
         let mut buf = PayloadHelper::new(once(Ok(buf.freeze())));
 
-        let frame = extract(Frame::parse(&mut buf, false, 1024));
+        let frame = extract(Frame::parse(&mut buf, false, 1024, 0));
         assert!(!frame.finished);
         assert_eq!(frame.opcode, OpCode::Text);
         assert_eq!(frame.payload.as_ref(), &b"1"[..]);
@@ -389,7 +817,7 @@ mod tests {
         let buf = BytesMut::from(&[0b00000001u8, 0b00000000u8][..]);
         let mut buf = PayloadHelper::new(once(Ok(buf.freeze())));
 
-        let frame = extract(Frame::parse(&mut buf, false, 1024));
+        let frame = extract(Frame::parse(&mut buf, false, 1024, 0));
         assert!(!frame.finished);
         assert_eq!(frame.opcode, OpCode::Text);
         assert!(frame.payload.is_empty());
@@ -399,14 +827,14 @@ mod tests {
     fn test_parse_length2() {
         let buf = BytesMut::from(&[0b00000001u8, 126u8][..]);
         let mut buf = PayloadHelper::new(once(Ok(buf.freeze())));
-        assert!(is_none(Frame::parse(&mut buf, false, 1024)));
+        assert!(is_none(Frame::parse(&mut buf, false, 1024, 0)));
 
         let mut buf = BytesMut::from(&[0b00000001u8, 126u8][..]);
         buf.extend(&[0u8, 4u8][..]);
         buf.extend(b"1234");
         let mut buf = PayloadHelper::new(once(Ok(buf.freeze())));
 
-        let frame = extract(Frame::parse(&mut buf, false, 1024));
+        let frame = extract(Frame::parse(&mut buf, false, 1024, 0));
         assert!(!frame.finished);
         assert_eq!(frame.opcode, OpCode::Text);
         assert_eq!(frame.payload.as_ref(), &b"1234"[..]);
@@ -416,14 +844,14 @@ mod tests {
     fn test_parse_length4() {
         let buf = BytesMut::from(&[0b00000001u8, 127u8][..]);
         let mut buf = PayloadHelper::new(once(Ok(buf.freeze())));
-        assert!(is_none(Frame::parse(&mut buf, false, 1024)));
+        assert!(is_none(Frame::parse(&mut buf, false, 1024, 0)));
 
         let mut buf = BytesMut::from(&[0b00000001u8, 127u8][..]);
         buf.extend(&[0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 4u8][..]);
         buf.extend(b"1234");
         let mut buf = PayloadHelper::new(once(Ok(buf.freeze())));
 
-        let frame = extract(Frame::parse(&mut buf, false, 1024));
+        let frame = extract(Frame::parse(&mut buf, false, 1024, 0));
         assert!(!frame.finished);
         assert_eq!(frame.opcode, OpCode::Text);
         assert_eq!(frame.payload.as_ref(), &b"1234"[..]);
@@ -436,9 +864,9 @@ mod tests {
         buf.extend(b"1");
         let mut buf = PayloadHelper::new(once(Ok(buf.freeze())));
 
-        assert!(Frame::parse(&mut buf, false, 1024).is_err());
+        assert!(Frame::parse(&mut buf, false, 1024, 0).is_err());
 
-        let frame = extract(Frame::parse(&mut buf, true, 1024));
+        let frame = extract(Frame::parse(&mut buf, true, 1024, 0));
         assert!(!frame.finished);
         assert_eq!(frame.opcode, OpCode::Text);
         assert_eq!(frame.payload, vec![1u8].into());
@@ -450,9 +878,9 @@ mod tests {
         buf.extend(&[1u8]);
         let mut buf = PayloadHelper::new(once(Ok(buf.freeze())));
 
-        assert!(Frame::parse(&mut buf, true, 1024).is_err());
+        assert!(Frame::parse(&mut buf, true, 1024, 0).is_err());
 
-        let frame = extract(Frame::parse(&mut buf, false, 1024));
+        let frame = extract(Frame::parse(&mut buf, false, 1024, 0));
         assert!(!frame.finished);
         assert_eq!(frame.opcode, OpCode::Text);
         assert_eq!(frame.payload, vec![1u8].into());
@@ -464,9 +892,180 @@ mod tests {
         buf.extend(&[1u8, 1u8]);
         let mut buf = PayloadHelper::new(once(Ok(buf.freeze())));
 
-        assert!(Frame::parse(&mut buf, true, 1).is_err());
+        assert!(Frame::parse(&mut buf, true, 1, 0).is_err());
+
+        if let Err(ProtocolError::Overflow) = Frame::parse(&mut buf, false, 0, 0) {
+        } else {
+            unreachable!("error");
+        }
+    }
+
+    #[test]
+    fn test_parse_rejects_unnegotiated_rsv_bits() {
+        // RSV1 set, no extension negotiated
+        let buf = BytesMut::from(&[0b01000001u8, 0b00000000u8][..]);
+        let mut buf = PayloadHelper::new(once(Ok(buf.freeze())));
+
+        if let Err(ProtocolError::InvalidRsvBits(_)) = Frame::parse(&mut buf, false, 1024, 0) {
+        } else {
+            unreachable!("error");
+        }
+    }
+
+    #[test]
+    fn test_parse_rejects_reserved_opcode() {
+        // opcode 0x3, a reserved data opcode
+        let buf = BytesMut::from(&[0b10000011u8, 0b00000000u8][..]);
+        let mut buf = PayloadHelper::new(once(Ok(buf.freeze())));
+
+        if let Err(ProtocolError::InvalidOpcode(0x3)) = Frame::parse(&mut buf, false, 1024, 0) {
+        } else {
+            unreachable!("error");
+        }
+    }
+
+    #[test]
+    fn test_parse_rejects_fragmented_control_frame() {
+        // Ping (0x9) without the FIN bit set
+        let buf = BytesMut::from(&[0b00001001u8, 0b00000000u8][..]);
+        let mut buf = PayloadHelper::new(once(Ok(buf.freeze())));
+
+        if let Err(ProtocolError::FragmentedControl) = Frame::parse(&mut buf, false, 1024, 0) {
+        } else {
+            unreachable!("error");
+        }
+    }
+
+    #[test]
+    fn test_compressed_message_roundtrip() {
+        let mut compress = Compress::new(Compression::default(), false);
+        let frame = Frame::compressed_message(
+            Vec::from("data data data data"), OpCode::Text, true, false, &mut compress).unwrap();
+
+        // RSV1 must be set on a compressed frame
+        assert_eq!(frame.as_ref()[0] & 0x40, 0x40);
+
+        let buf = BytesMut::from(frame.as_ref());
+        let mut buf = PayloadHelper::new(once(Ok(buf.freeze())));
+
+        let frame = extract(Frame::parse(&mut buf, false, 1024, RSV1));
+        assert!(frame.finished);
+        assert!(frame.compressed);
+        assert_eq!(frame.opcode, OpCode::Text);
+        // Frame::parse never inflates; FrameReassembler does, once per message.
+        assert_ne!(frame.payload.as_ref(), &b"data data data data"[..]);
+
+        let mut decompress = Decompress::new(false);
+        let mut reassembler = FrameReassembler::new(1024);
+        let msg = reassembler.process(frame, Some(&mut decompress)).unwrap().unwrap();
+        assert!(msg.finished);
+        assert_eq!(msg.opcode, OpCode::Text);
+        assert_eq!(msg.payload.as_ref(), &b"data data data data"[..]);
+    }
+
+    #[test]
+    fn test_compressed_message_roundtrip_with_context_takeover() {
+        // `compress`/`decompress` must be reused across messages unless
+        // context takeover was negotiated off; a second message reusing
+        // them must still inflate back to its own payload.
+        let mut compress = Compress::new(Compression::default(), false);
+        let mut decompress = Decompress::new(false);
+
+        for payload in &["data data data data", "more more more more"] {
+            let frame = Frame::compressed_message(
+                Vec::from(*payload), OpCode::Text, true, false, &mut compress).unwrap();
+
+            let buf = BytesMut::from(frame.as_ref());
+            let mut buf = PayloadHelper::new(once(Ok(buf.freeze())));
+            let frame = extract(Frame::parse(&mut buf, false, 1024, RSV1));
+
+            let mut reassembler = FrameReassembler::new(1024);
+            let msg = reassembler.process(frame, Some(&mut decompress)).unwrap().unwrap();
+            assert_eq!(msg.payload.as_ref(), payload.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_compressed_fragmented_message_roundtrip() {
+        // Only the first physical frame of a compressed message carries
+        // RSV1 (and hence `compressed == true`); the reassembler must carry
+        // that across `Continuation` frames and inflate the concatenated
+        // payload exactly once, at the end.
+        let mut compress = Compress::new(Compression::default(), false);
+        let compressed = Frame::deflate(&mut compress, b"data data data data").unwrap();
+
+        let first = Frame {
+            finished: false, opcode: OpCode::Text, payload: compressed,
+            close_reason: None, compressed: true };
+        let second = Frame {
+            finished: true, opcode: OpCode::Continuation, payload: Binary::from(&b""[..]),
+            close_reason: None, compressed: false };
+
+        let mut decompress = Decompress::new(false);
+        let mut reassembler = FrameReassembler::new(1024);
+        assert!(reassembler.process(first, Some(&mut decompress)).unwrap().is_none());
+        let msg = reassembler.process(second, Some(&mut decompress)).unwrap().unwrap();
+        assert!(msg.finished);
+        assert_eq!(msg.opcode, OpCode::Text);
+        assert_eq!(msg.payload.as_ref(), &b"data data data data"[..]);
+    }
 
-        if let Err(ProtocolError::Overflow) = Frame::parse(&mut buf, false, 0) {
+    #[test]
+    fn test_inflate_enforces_max_size() {
+        let mut compress = Compress::new(Compression::default(), false);
+        let payload = vec![b'a'; 4096];
+        let compressed = Frame::deflate(&mut compress, &payload).unwrap();
+
+        let mut decompress = Decompress::new(false);
+        if let Err(ProtocolError::Overflow) = Frame::inflate(&mut decompress, compressed.as_ref(), 16) {
+        } else {
+            unreachable!("error");
+        }
+    }
+
+    #[test]
+    fn test_inflate_multiple_output_chunks() {
+        // a highly compressible payload whose decompressed size spans more
+        // than one internal 4096-byte output buffer
+        let mut compress = Compress::new(Compression::default(), false);
+        let payload = vec![b'a'; 5000];
+        let compressed = Frame::deflate(&mut compress, &payload).unwrap();
+
+        let mut decompress = Decompress::new(false);
+        let out = Frame::inflate(&mut decompress, compressed.as_ref(), 10000).unwrap();
+        assert_eq!(out.as_ref(), &payload[..]);
+    }
+
+    #[test]
+    fn test_encode_matches_message() {
+        let encoded = Frame::encode(BytesMut::from(&b"data"[..]), OpCode::Ping, true, false);
+
+        let mut combined = BytesMut::new();
+        combined.extend_from_slice(&encoded.head);
+        combined.extend_from_slice(&encoded.payload);
+
+        let combined: Binary = combined.freeze().into();
+        assert_eq!(combined, Frame::message(Vec::from("data"), OpCode::Ping, true, false));
+    }
+
+    #[test]
+    fn test_encode_fragmented() {
+        let frames = Frame::encode_fragmented(Vec::from("abcdef"), OpCode::Text, false, 2).unwrap();
+        assert_eq!(frames.len(), 3);
+
+        assert_eq!(&frames[0].head[..], &[0b00000001u8, 2u8][..]);
+        assert_eq!(&frames[0].payload[..], b"ab");
+        assert_eq!(&frames[1].head[..], &[0b00000000u8, 2u8][..]);
+        assert_eq!(&frames[1].payload[..], b"cd");
+        assert_eq!(&frames[2].head[..], &[0b10000000u8, 2u8][..]);
+        assert_eq!(&frames[2].payload[..], b"ef");
+    }
+
+    #[test]
+    fn test_encode_fragmented_rejects_zero_chunk_size() {
+        if let Err(ProtocolError::InvalidChunkSize(0)) =
+            Frame::encode_fragmented(Vec::from("abcdef"), OpCode::Text, false, 0)
+        {
         } else {
             unreachable!("error");
         }
@@ -498,4 +1097,117 @@ mod tests {
         v.extend(b"data");
         assert_eq!(frame, v.into());
     }
+
+    #[test]
+    fn test_parse_close_frame_with_reason() {
+        let frame = Frame::close(CloseCode::Normal, "bye", false);
+        let mut buf = PayloadHelper::new(once(Ok(frame.as_ref().to_vec().into())));
+
+        let frame = extract(Frame::parse(&mut buf, false, 1024, 0));
+        let reason = frame.close_reason().unwrap();
+        assert_eq!(reason.code, CloseCode::Normal);
+        assert_eq!(reason.description.as_ref().map(|s| s.as_str()), Some("bye"));
+    }
+
+    #[test]
+    fn test_parse_close_frame_empty_payload() {
+        let buf = BytesMut::from(&[0b10001000u8, 0b00000000u8][..]);
+        let mut buf = PayloadHelper::new(once(Ok(buf.freeze())));
+
+        let frame = extract(Frame::parse(&mut buf, false, 1024, 0));
+        assert!(frame.close_reason().is_none());
+    }
+
+    #[test]
+    fn test_parse_close_frame_invalid_length() {
+        // length 1: a code byte with no second byte is illegal
+        let buf = BytesMut::from(&[0b10001000u8, 0b00000001u8, 1u8][..]);
+        let mut buf = PayloadHelper::new(once(Ok(buf.freeze())));
+
+        if let Err(ProtocolError::InvalidLength(1)) = Frame::parse(&mut buf, false, 1024, 0) {
+        } else {
+            unreachable!("error");
+        }
+    }
+
+    #[test]
+    fn test_parse_close_frame_invalid_code() {
+        // 1005 is reserved and must never appear on the wire
+        let mut buf = BytesMut::from(&[0b10001000u8, 0b00000010u8][..]);
+        buf.extend(&[3u8, 0xedu8]);
+        let mut buf = PayloadHelper::new(once(Ok(buf.freeze())));
+
+        if let Err(ProtocolError::InvalidCloseCode(1005)) = Frame::parse(&mut buf, false, 1024, 0) {
+        } else {
+            unreachable!("error");
+        }
+    }
+
+    #[test]
+    fn test_parse_close_frame_invalid_utf8_reason() {
+        let mut buf = BytesMut::from(&[0b10001000u8, 0b00000100u8][..]);
+        buf.extend(&[3u8, 232u8]); // CloseCode::Normal
+        buf.extend(&[0xff, 0xff]); // not valid UTF-8
+        let mut buf = PayloadHelper::new(once(Ok(buf.freeze())));
+
+        if let Err(ProtocolError::InvalidUtf8) = Frame::parse(&mut buf, false, 1024, 0) {
+        } else {
+            unreachable!("error");
+        }
+    }
+
+    fn frame(opcode: OpCode, finished: bool, payload: &[u8]) -> Frame {
+        Frame { finished, opcode, payload: Binary::from(payload), close_reason: None, compressed: false }
+    }
+
+    #[test]
+    fn test_reassemble_fragmented_message() {
+        let mut reassembler = FrameReassembler::new(1024);
+
+        assert!(reassembler.process(frame(OpCode::Text, false, b"foo"), None).unwrap().is_none());
+        assert!(reassembler.process(frame(OpCode::Ping, true, b""), None).unwrap().is_some());
+        assert!(reassembler.process(frame(OpCode::Continuation, false, b"bar"), None).unwrap().is_none());
+
+        let msg = reassembler.process(frame(OpCode::Continuation, true, b"baz"), None).unwrap().unwrap();
+        assert!(msg.finished);
+        assert_eq!(msg.opcode, OpCode::Text);
+        assert_eq!(msg.payload.as_ref(), &b"foobarbaz"[..]);
+    }
+
+    #[test]
+    fn test_reassemble_unexpected_continuation() {
+        let mut reassembler = FrameReassembler::new(1024);
+        if let Err(ProtocolError::UnexpectedContinuation) =
+            reassembler.process(frame(OpCode::Continuation, true, b"x"), None)
+        {
+        } else {
+            unreachable!("error");
+        }
+    }
+
+    #[test]
+    fn test_reassemble_unfinished_message_reopened() {
+        let mut reassembler = FrameReassembler::new(1024);
+        reassembler.process(frame(OpCode::Text, false, b"foo"), None).unwrap();
+
+        // a second non-continuation data frame while one is still open is a
+        // distinct illegal sequence from an orphan Continuation frame
+        if let Err(ProtocolError::ContinuationExpected) =
+            reassembler.process(frame(OpCode::Binary, false, b"bar"), None)
+        {
+        } else {
+            unreachable!("error");
+        }
+    }
+
+    #[test]
+    fn test_reassemble_max_message_size() {
+        let mut reassembler = FrameReassembler::new(4);
+        reassembler.process(frame(OpCode::Text, false, b"foo"), None).unwrap();
+
+        if let Err(ProtocolError::Overflow) = reassembler.process(frame(OpCode::Continuation, true, b"bar"), None) {
+        } else {
+            unreachable!("error");
+        }
+    }
 }